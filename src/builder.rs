@@ -0,0 +1,91 @@
+//! Configuration for spawning stoppable threads.
+
+use crate::{DropPolicy, StopToken, Thread};
+use std::io;
+use std::thread;
+
+/// Thread factory, mirroring [`std::thread::Builder`], used to configure
+/// a stoppable thread before spawning it.
+///```
+/// use stoplight::Builder;
+///
+/// let th = Builder::new()
+///     .name("worker".into())
+///     .spawn(|stop| {
+///         while !stop.is_stopped() {}
+///         std::thread::current().name().map(String::from)
+///     })
+///     .unwrap();
+///
+/// assert_eq!(th.stop_and_join().unwrap().as_deref(), Some("worker"));
+///```
+#[derive(Debug, Default)]
+pub struct Builder {
+    name: Option<String>,
+    stack_size: Option<usize>,
+    drop_policy: DropPolicy,
+}
+
+impl Builder {
+    /// Create a builder with the default configuration.
+    pub fn new() -> Builder {
+        Builder::default()
+    }
+
+    /// Name the thread, used in panic messages and by debuggers/profilers.
+    pub fn name(mut self, name: String) -> Builder {
+        self.name = Some(name);
+        self
+    }
+
+    /// Set the stack size of the thread in bytes.
+    pub fn stack_size(mut self, size: usize) -> Builder {
+        self.stack_size = Some(size);
+        self
+    }
+
+    /// Set what happens to the thread when its handle is dropped without being joined.
+    pub fn drop_policy(mut self, policy: DropPolicy) -> Builder {
+        self.drop_policy = policy;
+        self
+    }
+
+    /// Spawn a stoppable thread with this configuration, returning an error
+    /// if the OS failed to create the thread.
+    pub fn spawn<F, T>(self, f: F) -> io::Result<Thread<T>>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let mut builder = thread::Builder::new();
+        if let Some(name) = self.name {
+            builder = builder.name(name);
+        }
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+
+        let mut th = Thread::spawn_std(builder, f)?;
+        th.set_drop_policy(self.drop_policy);
+        Ok(th)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_size() {
+        let th = Builder::new()
+            .stack_size(4 * 1024 * 1024)
+            .spawn(|_| {
+                // would overflow the default 2MiB stack of spawned threads.
+                let buf = [1u8; 3 * 1024 * 1024];
+                buf.iter().map(|&b| b as usize).sum::<usize>()
+            })
+            .unwrap();
+
+        assert_eq!(th.join().unwrap(), 3 * 1024 * 1024);
+    }
+}
@@ -0,0 +1,268 @@
+//! Collections of stoppable threads that are stopped and joined together.
+
+use crate::{StopToken, Thread};
+use std::any::Any;
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A group of [`Thread`]s that can be stopped and joined together.
+///```
+/// use stoplight::{Thread, ThreadGroup};
+///
+/// let mut group = ThreadGroup::new();
+/// for i in 0..4 {
+///     group.spawn(move |stop| {
+///         while !stop.is_stopped() {}
+///         i
+///     });
+/// }
+///
+/// group.stop_all();
+/// let results: Vec<_> = group.join_all().into_iter().map(Result::unwrap).collect();
+/// assert_eq!(results, [0, 1, 2, 3]);
+///```
+pub struct ThreadGroup<T> {
+    threads: Vec<Thread<T>>,
+}
+
+impl<T> ThreadGroup<T>
+where
+    T: Send + 'static,
+{
+    /// Create an empty group.
+    pub fn new() -> ThreadGroup<T> {
+        ThreadGroup {
+            threads: Vec::new(),
+        }
+    }
+
+    /// Add a thread to the group.
+    pub fn add(&mut self, th: Thread<T>) {
+        self.threads.push(th);
+    }
+
+    /// Spawn a new thread into the group, see [`Thread::spawn`].
+    pub fn spawn<F>(&mut self, f: F)
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        self.add(Thread::spawn(f));
+    }
+
+    /// Number of threads in the group.
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Returns true if the group has no threads.
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Signal every thread in the group to stop.
+    pub fn stop_all(&self) {
+        for th in &self.threads {
+            th.stop();
+        }
+    }
+
+    /// Wait for every thread in the group to exit, returning their results
+    /// in the order they were added.
+    pub fn join_all(self) -> Vec<Result<T, Box<dyn Any + Send + 'static>>> {
+        self.threads.into_iter().map(Thread::join).collect()
+    }
+
+    /// Signal every thread in the group to stop, then wait for them to exit.
+    pub fn stop_and_join_all(self) -> Vec<Result<T, Box<dyn Any + Send + 'static>>> {
+        self.stop_all();
+        self.join_all()
+    }
+}
+
+impl<T> Default for ThreadGroup<T>
+where
+    T: Send + 'static,
+{
+    fn default() -> ThreadGroup<T> {
+        ThreadGroup::new()
+    }
+}
+
+impl<T> FromIterator<Thread<T>> for ThreadGroup<T> {
+    fn from_iter<I: IntoIterator<Item = Thread<T>>>(iter: I) -> ThreadGroup<T> {
+        ThreadGroup {
+            threads: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<T> Extend<Thread<T>> for ThreadGroup<T> {
+    fn extend<I: IntoIterator<Item = Thread<T>>>(&mut self, iter: I) {
+        self.threads.extend(iter);
+    }
+}
+
+/// A set of stoppable threads whose results are joined in completion order.
+///```
+/// use stoplight::JoinSet;
+/// use std::time::Duration;
+///
+/// let mut set = JoinSet::new();
+/// set.spawn(|_| {
+///     std::thread::sleep(Duration::from_millis(100));
+///     "slow"
+/// });
+/// set.spawn(|_| "fast");
+///
+/// assert_eq!(set.join_next().unwrap().unwrap(), "fast");
+/// assert_eq!(set.join_next().unwrap().unwrap(), "slow");
+/// assert!(set.join_next().is_none());
+///```
+pub struct JoinSet<T> {
+    threads: HashMap<u64, Thread<T>>,
+    next_id: u64,
+    // threads send their id here when exiting.
+    tx: Sender<u64>,
+    rx: Receiver<u64>,
+}
+
+impl<T> JoinSet<T>
+where
+    T: Send + 'static,
+{
+    /// Create an empty set.
+    pub fn new() -> JoinSet<T> {
+        let (tx, rx) = channel();
+        JoinSet {
+            threads: HashMap::new(),
+            next_id: 0,
+            tx,
+            rx,
+        }
+    }
+
+    /// Spawn a new thread into the set, see [`Thread::spawn`].
+    pub fn spawn<F>(&mut self, f: F)
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let notify = Notify(self.tx.clone(), id);
+        let th = Thread::spawn(move |stop| {
+            // dropped on exit, even when panicking.
+            let _notify = notify;
+            f(stop)
+        });
+        self.threads.insert(id, th);
+    }
+
+    /// Number of threads in the set that have not been joined.
+    pub fn len(&self) -> usize {
+        self.threads.len()
+    }
+
+    /// Returns true if the set has no threads left to join.
+    pub fn is_empty(&self) -> bool {
+        self.threads.is_empty()
+    }
+
+    /// Signal every thread in the set to stop.
+    pub fn stop_all(&self) {
+        for th in self.threads.values() {
+            th.stop();
+        }
+    }
+
+    /// Wait for the next thread to exit and return its result,
+    /// or `None` if the set is empty.
+    pub fn join_next(&mut self) -> Option<Result<T, Box<dyn Any + Send + 'static>>> {
+        if self.is_empty() {
+            return None;
+        }
+
+        // we hold a sender ourselves, so this never hangs up.
+        let id = self.rx.recv().unwrap();
+        self.join_id(id)
+    }
+
+    /// Return the result of a thread that already exited, without blocking.
+    pub fn try_join_next(&mut self) -> Option<Result<T, Box<dyn Any + Send + 'static>>> {
+        let id = self.rx.try_recv().ok()?;
+        self.join_id(id)
+    }
+
+    fn join_id(&mut self, id: u64) -> Option<Result<T, Box<dyn Any + Send + 'static>>> {
+        self.threads.remove(&id).map(Thread::join)
+    }
+}
+
+impl<T> Default for JoinSet<T>
+where
+    T: Send + 'static,
+{
+    fn default() -> JoinSet<T> {
+        JoinSet::new()
+    }
+}
+
+struct Notify(Sender<u64>, u64);
+
+impl Drop for Notify {
+    fn drop(&mut self) {
+        // the set may have been dropped already.
+        let _ = self.0.send(self.1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_and_join_all() {
+        let group: ThreadGroup<_> = (0..3)
+            .map(|i| {
+                Thread::spawn(move |stop| {
+                    while !stop.is_stopped() {}
+                    if i == 1 {
+                        panic!("worker panicked");
+                    }
+                    i
+                })
+            })
+            .collect();
+        assert_eq!(group.len(), 3);
+
+        let results = group.stop_and_join_all();
+        assert_eq!(*results[0].as_ref().unwrap(), 0);
+        assert!(results[1].is_err());
+        assert_eq!(*results[2].as_ref().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_join_next_order() {
+        let mut set = JoinSet::new();
+        let (tx, rx) = channel::<()>();
+        set.spawn(move |_| {
+            rx.recv().unwrap();
+            1
+        });
+        set.spawn(|_| panic!("worker panicked"));
+        set.spawn(|stop| {
+            while !stop.is_stopped() {}
+            2
+        });
+
+        assert!(set.join_next().unwrap().is_err());
+        tx.send(()).unwrap();
+        assert_eq!(set.join_next().unwrap().unwrap(), 1);
+        assert!(set.try_join_next().is_none());
+
+        set.stop_all();
+        assert_eq!(set.join_next().unwrap().unwrap(), 2);
+        assert!(set.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+//! Periodic stoppable tasks.
+
+use crate::{StopToken, Thread};
+use std::time::{Duration, Instant};
+
+/// Spawn a thread calling `f` every `period` until stopped, starting right away.
+///
+/// Ticks are scheduled relative to the start so they don't drift with the time spent in `f`.
+/// If `f` takes longer than a period the missed ticks are skipped rather than run late.
+///```
+/// use stoplight::interval::spawn_periodic;
+/// use std::sync::mpsc::channel;
+/// use std::time::Duration;
+///
+/// let (tx, rx) = channel();
+/// let th = spawn_periodic(Duration::from_millis(10), move |_| tx.send(()).unwrap());
+///
+/// rx.iter().take(3).for_each(drop);
+/// th.stop_and_join().unwrap();
+///```
+///
+/// # Panics
+///
+/// Panics if `period` is zero.
+pub fn spawn_periodic<F>(period: Duration, mut f: F) -> Thread<()>
+where
+    F: FnMut(&StopToken) + Send + 'static,
+{
+    assert!(period > Duration::from_secs(0), "period must be non-zero");
+
+    Thread::spawn(move |stop| {
+        let mut next = Instant::now();
+        while !stop.is_stopped() {
+            f(&stop);
+
+            next += period;
+            let now = Instant::now();
+            if next < now {
+                let missed = (now - next).as_nanos() / period.as_nanos() + 1;
+                next += period * missed as u32;
+            }
+
+            if stop.wait_timeout(next - now) {
+                break;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    #[test]
+    fn test_no_drift() {
+        let (tx, rx) = channel();
+        let th = spawn_periodic(Duration::from_millis(50), move |_| {
+            tx.send(Instant::now()).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        });
+
+        let ticks: Vec<_> = rx.iter().take(5).collect();
+        th.stop_and_join().unwrap();
+
+        // 20ms of drift per tick would add up to 80ms.
+        let elapsed = ticks[4] - ticks[0];
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(260), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn test_skip_missed_ticks() {
+        let (tx, rx) = channel();
+        let th = spawn_periodic(Duration::from_millis(10), move |_| {
+            tx.send(Instant::now()).unwrap();
+            thread::sleep(Duration::from_millis(25));
+        });
+
+        let ticks: Vec<_> = rx.iter().take(2).collect();
+        th.stop_and_join().unwrap();
+        assert!(ticks[1] - ticks[0] >= Duration::from_millis(30));
+    }
+}
@@ -3,11 +3,10 @@
 //! Stoplight is a small library for stoppable threads/tasks.
 //!```
 //! use stoplight::Thread;
-//! use std::sync::atomic::{AtomicBool, Ordering};
 //!
 //! // spawn our task, this creates a new OS thread.
 //! let th = Thread::spawn(|stop| {
-//!     while !stop.load(Ordering::Relaxed) {}
+//!     while !stop.is_stopped() {}
 //!     42
 //! });
 //!
@@ -15,63 +14,46 @@
 //! th.stop();
 //! assert_eq!(th.join().unwrap(), 42);
 //!```
+//!
+//! Periodic workers can wait on their token instead of sleeping, which is backed by a
+//! condvar and wakes up as soon as stop is requested.
+//!```
+//! use stoplight::Thread;
+//! use std::time::Duration;
+//!
+//! let th = Thread::spawn(|stop| {
+//!     let mut runs = 0;
+//!     // instead of thread::sleep(Duration::from_secs(5))
+//!     while !stop.wait_timeout(Duration::from_secs(5)) {
+//!         runs += 1;
+//!     }
+//!     runs
+//! });
+//!
+//! assert_eq!(th.stop_and_join().unwrap(), 0);
+//!```
 
-use std::any::Any;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::thread::JoinHandle;
-
-/// Handle to a stoppable thread.
-pub struct Thread<T> {
-    jh: JoinHandle<T>,
-    stop: Arc<AtomicBool>,
-}
-
-impl<T> Thread<T>
-where
-    T: Send + 'static,
-{
-    /// Spawn a new job with cancelation.
-    pub fn spawn<F>(f: F) -> Thread<T>
-    where
-        F: FnOnce(Arc<AtomicBool>) -> T + Send + 'static,
-    {
-        let stop = Arc::new(AtomicBool::new(false));
-
-        Thread {
-            stop: stop.clone(),
-            jh: thread::spawn(move || f(stop)),
-        }
-    }
-
-    /// Join waits for the thread to exit then returns the return value.
-    pub fn join(self) -> Result<T, Box<(dyn Any + Send + 'static)>> {
-        self.jh.join()
-    }
-
-    /// Signal the Thread to stop, NOTE: This does not ensure the thread has stopped
-    /// but only that the signal has been sent.
-    // TODO: Clean up type signature of Result<T, E> (copied off compile errors)
-    pub fn stop(&self) {
-        self.stop.store(true, Ordering::Relaxed);
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::time::Duration;
-
-    #[test]
-    fn test_busy_loop() {
-        let th = Thread::spawn(|stop| {
-            thread::sleep(Duration::from_millis(300));
-            while !stop.load(Ordering::Relaxed) {}
-            42
-        });
-
-        th.stop();
-        assert_eq!(th.join().unwrap(), 42);
-    }
-}
+mod builder;
+mod group;
+pub mod interval;
+pub mod pool;
+mod progress;
+mod recv;
+pub mod schedule;
+mod scope;
+mod stream;
+mod task_scope;
+mod thread;
+mod timer;
+mod token;
+pub mod watchdog;
+
+pub use builder::Builder;
+pub use group::{JoinSet, ThreadGroup};
+pub use progress::{Progress, ProgressThread};
+pub use recv::{RecvError, RecvTimeoutError};
+pub use scope::{scope, Scope, ScopedThread};
+pub use stream::{Sink, StreamIter, StreamThread};
+pub use task_scope::{task_scope, TaskScope};
+pub use thread::{join_any, DropPolicy, JoinTimeout, Thread};
+pub use token::{sleep, StopSource, StopToken};
@@ -0,0 +1,161 @@
+//! A fixed size pool of stoppable worker threads.
+//!```
+//! use stoplight::pool::Pool;
+//! use std::sync::mpsc::channel;
+//!
+//! let pool = Pool::new(4);
+//! let (tx, rx) = channel();
+//!
+//! for i in 0..8 {
+//!     let tx = tx.clone();
+//!     pool.execute(move |_stop| tx.send(i * i).unwrap());
+//! }
+//!
+//! let mut squares: Vec<i32> = rx.iter().take(8).collect();
+//! squares.sort();
+//! assert_eq!(squares, [0, 1, 4, 9, 16, 25, 36, 49]);
+//!
+//! pool.shutdown();
+//!```
+
+use crate::{StopSource, StopToken, Thread};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+type Job = Box<dyn FnOnce(StopToken) + Send + 'static>;
+
+/// Pool of worker threads executing jobs, sharing one cooperative stop signal.
+///
+/// Every job is given a [`StopToken`] that is stopped when the pool shuts down,
+/// dropping the pool signals stop without waiting for the workers.
+pub struct Pool {
+    // None once shutting down, which makes idle workers exit.
+    jobs: Option<Sender<Job>>,
+    workers: Vec<Thread<()>>,
+    stop: StopSource,
+}
+
+impl Pool {
+    /// Create a pool with `size` worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero or the OS fails to create a thread.
+    pub fn new(size: usize) -> Pool {
+        assert!(size > 0, "pool must have at least one worker");
+
+        let stop = StopSource::new();
+        let (tx, rx) = channel();
+        let rx = Arc::new(Mutex::new(rx));
+
+        let workers = (0..size)
+            .map(|_| {
+                let rx = rx.clone();
+                let token = stop.token();
+                Thread::spawn(move |_| work(&rx, &token))
+            })
+            .collect();
+
+        Pool {
+            jobs: Some(tx),
+            workers,
+            stop,
+        }
+    }
+
+    /// Queue a job to be run by the next idle worker.
+    ///
+    /// A panicking job does not take down its worker.
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce(StopToken) + Send + 'static,
+    {
+        if let Some(jobs) = &self.jobs {
+            // workers only hang up once the pool shuts down.
+            let _ = jobs.send(Box::new(f));
+        }
+    }
+
+    /// Signal stop to every job, then wait for all workers to exit.
+    /// Jobs still queued are not run.
+    pub fn shutdown(mut self) {
+        self.signal_stop();
+        for worker in self.workers.drain(..) {
+            // jobs already run under catch_unwind.
+            let _ = worker.join();
+        }
+    }
+
+    fn signal_stop(&mut self) {
+        self.stop.stop();
+        self.jobs = None;
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        self.signal_stop();
+    }
+}
+
+fn work(jobs: &Mutex<Receiver<Job>>, stop: &StopToken) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        if stop.is_stopped() {
+            return;
+        }
+
+        let token = stop.clone();
+        let _ = panic::catch_unwind(AssertUnwindSafe(move || job(token)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_shutdown_stops_jobs() {
+        let pool = Pool::new(2);
+        let (started, running) = channel();
+        let stopped = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..2 {
+            let started = started.clone();
+            let stopped = stopped.clone();
+            pool.execute(move |stop| {
+                started.send(()).unwrap();
+                while !stop.is_stopped() {}
+                stopped.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        // never runs, both workers are busy until shutdown.
+        let ran = Arc::new(AtomicUsize::new(0));
+        let queued = ran.clone();
+        pool.execute(move |_| {
+            queued.fetch_add(1, Ordering::Relaxed);
+        });
+
+        running.iter().take(2).for_each(drop);
+        pool.shutdown();
+        assert_eq!(stopped.load(Ordering::Relaxed), 2);
+        assert_eq!(ran.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_panicking_job() {
+        let pool = Pool::new(1);
+        let (tx, rx) = channel();
+
+        pool.execute(|_| panic!("job panicked"));
+        pool.execute(move |_| tx.send(42).unwrap());
+
+        assert_eq!(rx.recv().unwrap(), 42);
+        pool.shutdown();
+    }
+}
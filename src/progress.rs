@@ -0,0 +1,114 @@
+//! Reporting progress from a stoppable thread to its owner.
+
+use crate::{StopToken, Thread};
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+
+/// Reporter handed to a thread spawned with [`Thread::spawn_with_progress`],
+/// used to publish its latest progress.
+pub struct Progress<P> {
+    latest: Arc<Mutex<Option<P>>>,
+}
+
+impl<P> Progress<P> {
+    /// Replace the latest progress with `progress`.
+    pub fn set(&self, progress: P) {
+        *self.latest.lock().unwrap() = Some(progress);
+    }
+}
+
+/// Handle to a stoppable thread reporting progress, see [`Thread::spawn_with_progress`].
+pub struct ProgressThread<T, P> {
+    thread: Thread<T>,
+    latest: Arc<Mutex<Option<P>>>,
+}
+
+impl<T, P> ProgressThread<T, P>
+where
+    T: Send + 'static,
+{
+    /// The latest progress reported, or `None` if there wasn't any yet.
+    pub fn progress(&self) -> Option<P>
+    where
+        P: Clone,
+    {
+        self.latest.lock().unwrap().clone()
+    }
+
+    /// The underlying thread handle.
+    pub fn thread(&self) -> &Thread<T> {
+        &self.thread
+    }
+
+    /// Signal the thread to stop, see [`Thread::stop`].
+    pub fn stop(&self) {
+        self.thread.stop();
+    }
+
+    /// Join waits for the thread to exit then returns the return value.
+    pub fn join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.thread.join()
+    }
+
+    /// Stop reporting progress, returning the plain thread handle.
+    pub fn into_thread(self) -> Thread<T> {
+        self.thread
+    }
+}
+
+impl<T> Thread<T>
+where
+    T: Send + 'static,
+{
+    /// Spawn a new job with cancelation, which can report progress to the returned handle.
+    ///```
+    /// use stoplight::Thread;
+    /// use std::time::Duration;
+    ///
+    /// let th = Thread::spawn_with_progress(|stop, progress| {
+    ///     for i in 1..=10 {
+    ///         progress.set(i as f64 / 10.0);
+    ///         stop.wait_timeout(Duration::from_millis(1));
+    ///     }
+    /// });
+    ///
+    /// while th.progress() != Some(1.0) {}
+    /// th.join().unwrap();
+    ///```
+    pub fn spawn_with_progress<F, P>(f: F) -> ProgressThread<T, P>
+    where
+        F: FnOnce(StopToken, Progress<P>) -> T + Send + 'static,
+        P: Send + 'static,
+    {
+        let latest = Arc::new(Mutex::new(None));
+        let progress = Progress {
+            latest: latest.clone(),
+        };
+
+        ProgressThread {
+            thread: Thread::spawn(move |stop| f(stop, progress)),
+            latest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let th = Thread::spawn_with_progress(|stop, progress| {
+            progress.set("started");
+            stop.wait();
+            progress.set("stopped");
+        });
+
+        while th.progress().is_none() {}
+        assert_eq!(th.progress(), Some("started"));
+
+        th.stop();
+        while th.progress() != Some("stopped") {}
+        th.into_thread().join().unwrap();
+    }
+}
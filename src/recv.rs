@@ -0,0 +1,136 @@
+//! Stop-aware receiving from [`std::sync::mpsc`] channels.
+
+use crate::StopToken;
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+// how often a blocked receive checks for stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Error returned by [`StopToken::recv`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvError {
+    /// Stop was requested before a message arrived.
+    Stopped,
+    /// Every sender hung up.
+    Disconnected,
+}
+
+/// Error returned by [`StopToken::recv_timeout`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    /// Stop was requested before a message arrived.
+    Stopped,
+    /// No message arrived before the timeout.
+    Timeout,
+    /// Every sender hung up.
+    Disconnected,
+}
+
+impl StopToken {
+    /// Block until a message arrives on `rx`, returning [`RecvError::Stopped`]
+    /// if stop is requested first.
+    ///```
+    /// use stoplight::{RecvError, Thread};
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let (tx, rx) = channel::<()>();
+    /// let th = Thread::spawn(move |stop| stop.recv(&rx));
+    ///
+    /// assert_eq!(th.stop_and_join().unwrap(), Err(RecvError::Stopped));
+    /// drop(tx);
+    ///```
+    pub fn recv<T>(&self, rx: &Receiver<T>) -> Result<T, RecvError> {
+        loop {
+            match self.recv_timeout(rx, POLL_INTERVAL) {
+                Ok(msg) => return Ok(msg),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Stopped) => return Err(RecvError::Stopped),
+                Err(RecvTimeoutError::Disconnected) => return Err(RecvError::Disconnected),
+            }
+        }
+    }
+
+    /// Block until a message arrives on `rx` or `timeout` elapses, returning
+    /// [`RecvTimeoutError::Stopped`] if stop is requested first.
+    pub fn recv_timeout<T>(
+        &self,
+        rx: &Receiver<T>,
+        timeout: Duration,
+    ) -> Result<T, RecvTimeoutError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.is_stopped() {
+                return Err(RecvTimeoutError::Stopped);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            match rx.recv_timeout(POLL_INTERVAL.min(deadline - now)) {
+                Ok(msg) => return Ok(msg),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(RecvTimeoutError::Disconnected)
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Stopped => f.write_str("stop requested while receiving"),
+            RecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for RecvError {}
+
+impl fmt::Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Stopped => f.write_str("stop requested while receiving"),
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on channel"),
+            RecvTimeoutError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StopSource;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_recv() {
+        let source = StopSource::new();
+        let token = source.token();
+        let (tx, rx) = channel();
+
+        tx.send(1).unwrap();
+        assert_eq!(token.recv(&rx), Ok(1));
+        assert_eq!(
+            token.recv_timeout(&rx, Duration::from_millis(20)),
+            Err(RecvTimeoutError::Timeout)
+        );
+
+        source.stop();
+        tx.send(2).unwrap();
+        assert_eq!(token.recv(&rx), Err(RecvError::Stopped));
+
+        let source = StopSource::new();
+        drop(tx);
+        assert_eq!(source.token().recv(&rx), Ok(2));
+        assert_eq!(source.token().recv(&rx), Err(RecvError::Disconnected));
+    }
+}
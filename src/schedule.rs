@@ -0,0 +1,343 @@
+//! Running stoppable jobs on a schedule, either at a fixed interval or cron-like.
+//!```
+//! use stoplight::schedule::{Schedule, Scheduler};
+//! use std::time::Duration;
+//!
+//! let mut scheduler = Scheduler::new();
+//! scheduler.add(Schedule::every(Duration::from_secs(30)), |_| println!("heartbeat"));
+//! // every day at 03:15 UTC
+//! scheduler.add("15 3 * * *".parse().unwrap(), |stop| {
+//!     for _table in 0..100 {
+//!         if stop.is_stopped() {
+//!             return;
+//!         }
+//!         // vacuum table
+//!     }
+//! });
+//!
+//! scheduler.shutdown();
+//!```
+
+use crate::{StopSource, StopToken, ThreadGroup};
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// When to run a job.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    kind: Kind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Every(Duration),
+    Cron(Cron),
+}
+
+/// Bitmasks of the allowed values of each cron field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // cron matches either day field when both are restricted.
+    any_day: bool,
+}
+
+/// Error returned when parsing an invalid cron expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    msg: String,
+}
+
+impl Schedule {
+    /// Run every `period`, starting one period from now.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn every(period: Duration) -> Schedule {
+        assert!(period > Duration::from_secs(0), "period must be non-zero");
+        Schedule {
+            kind: Kind::Every(period),
+        }
+    }
+
+    /// Parse a standard 5 field cron expression: minute, hour, day of month, month and
+    /// day of week (0 or 7 is Sunday). Fields support `*`, `a-b` ranges, `,` lists and
+    /// `/n` steps. Times are in UTC.
+    pub fn cron(expr: &str) -> Result<Schedule, ParseError> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(ParseError::new(format!(
+                "expected 5 fields, got {}",
+                fields.len()
+            )));
+        }
+
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        // 7 is an alias for Sunday.
+        if weekdays & 1 << 7 != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(Schedule {
+            kind: Kind::Cron(Cron {
+                minutes: parse_field(fields[0], 0, 59)?,
+                hours: parse_field(fields[1], 0, 23)?,
+                days: parse_field(fields[2], 1, 31)?,
+                months: parse_field(fields[3], 1, 12)?,
+                weekdays,
+                any_day: fields[2] != "*" && fields[4] != "*",
+            }),
+        })
+    }
+
+    /// The first time strictly after `time` this schedule fires,
+    /// or `None` if it never fires again.
+    pub fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        match &self.kind {
+            Kind::Every(period) => Some(time + *period),
+            Kind::Cron(cron) => cron.next_after(time),
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Schedule, ParseError> {
+        Schedule::cron(s)
+    }
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, ParseError> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_num(step)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(ParseError::new(format!("zero step in {:?}", part)));
+        }
+
+        let (lo, hi) = if range == "*" {
+            (min, max)
+        } else if let Some((lo, hi)) = range.split_once('-') {
+            (parse_num(lo)?, parse_num(hi)?)
+        } else {
+            let n = parse_num(range)?;
+            // `n/step` means from n to the maximum.
+            (n, if part.contains('/') { max } else { n })
+        };
+        if lo < min || hi > max || lo > hi {
+            return Err(ParseError::new(format!(
+                "{:?} out of range {}-{}",
+                part, min, max
+            )));
+        }
+
+        for n in (lo..=hi).step_by(step as usize) {
+            mask |= 1 << n;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_num(s: &str) -> Result<u32, ParseError> {
+    s.parse()
+        .map_err(|_| ParseError::new(format!("invalid number {:?}", s)))
+}
+
+impl Cron {
+    fn next_after(&self, time: SystemTime) -> Option<SystemTime> {
+        let secs = time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+        let start = secs / 60 + 1;
+        let (mut day, mut minute) = (start / 1440, start % 1440);
+
+        // long enough to always find Feb 29th.
+        for _ in 0..366 * 9 {
+            if self.matches_day(day) {
+                for m in minute..1440 {
+                    if self.hours & 1 << (m / 60) != 0 && self.minutes & 1 << (m % 60) != 0 {
+                        return Some(UNIX_EPOCH + Duration::from_secs((day * 1440 + m) * 60));
+                    }
+                }
+            }
+            day += 1;
+            minute = 0;
+        }
+        None
+    }
+
+    fn matches_day(&self, days: u64) -> bool {
+        let (month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday.
+        let weekday = (days + 4) % 7;
+
+        let day_ok = self.days & 1 << day != 0;
+        let weekday_ok = self.weekdays & 1 << weekday != 0;
+        let day_ok = if self.any_day {
+            day_ok || weekday_ok
+        } else {
+            day_ok && weekday_ok
+        };
+        day_ok && self.months & 1 << month != 0
+    }
+}
+
+/// Month and day of a count of days since 1970-01-01, see
+/// <http://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: u64) -> (u32, u32) {
+    let z = days + 719_468;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (month as u32, day as u32)
+}
+
+impl ParseError {
+    fn new(msg: String) -> ParseError {
+        ParseError { msg }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid cron expression: {}", self.msg)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Runs jobs on their [`Schedule`], each on its own stoppable thread.
+///
+/// Jobs are given a [`StopToken`] stopped when the scheduler stops, so long running jobs
+/// can be cancelled mid-run. A panicking job is skipped until its next run.
+#[derive(Default)]
+pub struct Scheduler {
+    jobs: ThreadGroup<()>,
+    stop: StopSource,
+}
+
+impl Scheduler {
+    /// Create a scheduler without any jobs.
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Run `f` on `schedule` until the scheduler stops.
+    pub fn add<F>(&mut self, schedule: Schedule, mut f: F)
+    where
+        F: FnMut(&StopToken) + Send + 'static,
+    {
+        let scheduler = self.stop.token();
+        self.jobs.spawn(move |own| {
+            let stop = StopToken::any_of(&[own, scheduler]);
+
+            let mut prev = SystemTime::now();
+            while let Some(mut next) = schedule.next_after(prev) {
+                let now = SystemTime::now();
+                // skip runs missed while the previous one was running.
+                while next < now {
+                    next = match schedule.next_after(next) {
+                        Some(next) => next,
+                        None => return,
+                    };
+                }
+
+                // the wall clock may be changed while sleeping.
+                let wait = next.duration_since(now).unwrap_or_default();
+                if stop.wait_timeout(wait) {
+                    return;
+                }
+                if SystemTime::now() < next {
+                    continue;
+                }
+
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| f(&stop)));
+                prev = next;
+            }
+        });
+    }
+
+    /// Signal every job to stop, without waiting for them.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Signal every job to stop, then wait for all running jobs to return.
+    pub fn shutdown(self) {
+        self.stop();
+        self.jobs.join_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    // 2024-02-28 23:59:30 UTC, a Wednesday.
+    const T: u64 = 1_709_164_770;
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn next(expr: &str, secs: u64) -> Option<u64> {
+        let next = Schedule::cron(expr).unwrap().next_after(at(secs))?;
+        Some(next.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn test_cron_next() {
+        assert_eq!(next("* * * * *", T), Some(T + 30));
+        // leap day
+        assert_eq!(next("0 12 29 2 *", T), Some(T + 30 + 12 * 3600));
+        // sunday, March 3rd
+        assert_eq!(
+            next("30 8 * * 7", T),
+            Some(T + 30 + 3 * 86400 + 8 * 3600 + 1800)
+        );
+        // either the 1st or a friday, whichever comes first.
+        assert_eq!(next("0 0 1 * 5", T), Some(T + 30 + 86400));
+        assert_eq!(next("*/15 * * * *", T + 31), Some(T + 30 + 15 * 60));
+        assert_eq!(next("0 0 31 2 *", T), None);
+    }
+
+    #[test]
+    fn test_cron_parse_errors() {
+        for expr in [
+            "* * * *",
+            "60 * * * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "x * * * *",
+        ] {
+            assert!(Schedule::cron(expr).is_err(), "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::new();
+        let (tx, rx) = channel();
+        scheduler.add(Schedule::every(Duration::from_millis(10)), move |_| {
+            let _ = tx.send(());
+        });
+        scheduler.add(Schedule::every(Duration::from_secs(60)), |_| unreachable!());
+
+        rx.iter().take(3).for_each(drop);
+        scheduler.shutdown();
+    }
+}
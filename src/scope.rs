@@ -0,0 +1,175 @@
+//! Scoped stoppable threads, which can borrow non-`'static` data.
+
+use crate::{StopSource, StopToken};
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::ScopedJoinHandle;
+
+/// Create a scope for spawning stoppable threads that can borrow from the enclosing stack.
+///
+/// Once `f` returns (or panics) every thread spawned in the scope is signaled to stop,
+/// then joined before `scope` returns. As with [`std::thread::scope`], a panic in a thread
+/// that was not joined manually is propagated.
+///```
+/// let mut results = vec![0; 4];
+///
+/// stoplight::scope(|s| {
+///     for (i, res) in results.iter_mut().enumerate() {
+///         s.spawn(move |_| *res = i * 10);
+///     }
+///
+///     // stopped when the scope ends.
+///     s.spawn(|stop| while !stop.is_stopped() {});
+/// });
+///
+/// assert_eq!(results, [0, 10, 20, 30]);
+///```
+pub fn scope<'env, F, R>(f: F) -> R
+where
+    F: for<'scope> FnOnce(&Scope<'scope, 'env>) -> R,
+{
+    thread::scope(|inner| {
+        let scope = Scope {
+            inner,
+            shared: Arc::default(),
+        };
+
+        // dropped before the std scope joins the threads, even when f panics.
+        let _end = End(scope.clone());
+        f(&scope)
+    })
+}
+
+/// A scope to spawn stoppable threads in, see [`scope`].
+///
+/// Scopes can be cloned and moved into spawned threads to spawn nested threads.
+#[derive(Clone)]
+pub struct Scope<'scope, 'env: 'scope> {
+    inner: &'scope thread::Scope<'scope, 'env>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+#[derive(Default)]
+struct Shared {
+    sources: Vec<StopSource>,
+    // set once the scope body returned, threads spawned after this are stopped immediately.
+    ended: bool,
+}
+
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawn a stoppable thread within the scope.
+    pub fn spawn<F, T>(&self, f: F) -> ScopedThread<'scope, T>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'scope,
+        T: Send + 'scope,
+    {
+        let stop = StopSource::new();
+        let token = stop.token();
+
+        let mut shared = self.shared.lock().unwrap();
+        if shared.ended {
+            stop.stop();
+        }
+        shared.sources.push(stop.clone());
+        drop(shared);
+
+        let unpark = token.clone();
+        let jh = self.inner.spawn(move || f(token));
+        unpark.unpark_on_stop(jh.thread().clone());
+
+        ScopedThread { jh, stop }
+    }
+
+    /// Signal every thread spawned in the scope so far to stop.
+    pub fn stop_all(&self) {
+        for source in self.shared.lock().unwrap().sources.iter() {
+            source.stop();
+        }
+    }
+}
+
+/// Handle to a stoppable thread spawned in a [`Scope`].
+pub struct ScopedThread<'scope, T> {
+    jh: ScopedJoinHandle<'scope, T>,
+    stop: StopSource,
+}
+
+impl<'scope, T> ScopedThread<'scope, T> {
+    /// Join waits for the thread to exit then returns the return value.
+    pub fn join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.jh.join()
+    }
+
+    /// Signal the thread to stop, without waiting for it to exit.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Signal the thread to stop, then wait for it to exit and return its return value.
+    pub fn stop_and_join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.stop();
+        self.join()
+    }
+
+    /// Returns true once the thread has exited, either by returning or panicking.
+    pub fn is_finished(&self) -> bool {
+        self.jh.is_finished()
+    }
+
+    /// Returns true once the thread has been signaled to stop.
+    pub fn is_stop_requested(&self) -> bool {
+        self.stop.is_stopped()
+    }
+}
+
+struct End<'scope, 'env>(Scope<'scope, 'env>);
+
+impl Drop for End<'_, '_> {
+    fn drop(&mut self) {
+        let mut shared = self.0.shared.lock().unwrap();
+        shared.ended = true;
+        for source in shared.sources.iter() {
+            source.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_borrow_and_join() {
+        let stopped = AtomicUsize::new(0);
+        let data = [1, 2, 3];
+
+        let sum = scope(|s| {
+            for _ in 0..3 {
+                s.spawn(|stop| {
+                    while !stop.is_stopped() {}
+                    stopped.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+
+            s.spawn(|_| data.iter().sum::<i32>()).join().unwrap()
+        });
+
+        assert_eq!(sum, 6);
+        assert_eq!(stopped.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_stop_on_panic() {
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            scope(|s| {
+                let nested = s.clone();
+                s.spawn(move |_| nested.spawn(|stop| while !stop.is_stopped() {}));
+                panic!("scope body panicked");
+            })
+        }));
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,143 @@
+//! Stoppable threads producing a stream of values.
+
+use crate::{StopToken, Thread};
+use std::any::Any;
+use std::sync::mpsc::{self, channel, Receiver, Sender};
+
+/// Sending half handed to a thread spawned with [`Thread::spawn_stream`].
+pub struct Sink<I> {
+    tx: Sender<I>,
+    stop: StopToken,
+}
+
+impl<I> Sink<I> {
+    /// Yield `item` to the owner of the stream.
+    ///
+    /// Returns false if the item could not be delivered because stop was requested or the
+    /// receiving side is gone, in which case the producer should return.
+    pub fn send(&self, item: I) -> bool {
+        !self.stop.is_stopped() && self.tx.send(item).is_ok()
+    }
+}
+
+/// Handle to a stoppable thread yielding values, see [`Thread::spawn_stream`].
+///
+/// Iterating the handle receives the values until the producer returns.
+pub struct StreamThread<I> {
+    thread: Thread<()>,
+    rx: Receiver<I>,
+}
+
+impl<I> StreamThread<I>
+where
+    I: Send + 'static,
+{
+    /// Block until the next value, or `None` once the producer returned.
+    pub fn recv(&self) -> Option<I> {
+        self.rx.recv().ok()
+    }
+
+    /// Get the next value if one is ready, without blocking.
+    pub fn try_recv(&self) -> Option<I> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Iterate over the values, blocking until the producer returns.
+    pub fn iter(&self) -> mpsc::Iter<'_, I> {
+        self.rx.iter()
+    }
+
+    /// Signal the producer to stop, see [`Thread::stop`].
+    pub fn stop(&self) {
+        self.thread.stop();
+    }
+
+    /// Wait for the producer to exit, dropping any values not yet received.
+    pub fn join(self) -> Result<(), Box<dyn Any + Send + 'static>> {
+        self.thread.join()
+    }
+}
+
+impl<I> IntoIterator for StreamThread<I>
+where
+    I: Send + 'static,
+{
+    type Item = I;
+    type IntoIter = StreamIter<I>;
+
+    fn into_iter(self) -> StreamIter<I> {
+        StreamIter {
+            _thread: self.thread,
+            rx: self.rx.into_iter(),
+        }
+    }
+}
+
+/// Iterator over the values of a [`StreamThread`], stopping the producer when dropped.
+pub struct StreamIter<I> {
+    _thread: Thread<()>,
+    rx: mpsc::IntoIter<I>,
+}
+
+impl<I> Iterator for StreamIter<I> {
+    type Item = I;
+
+    fn next(&mut self) -> Option<I> {
+        self.rx.next()
+    }
+}
+
+impl Thread<()> {
+    /// Spawn a new job with cancelation yielding any number of values through its [`Sink`].
+    ///```
+    /// use stoplight::Thread;
+    ///
+    /// let naturals = Thread::spawn_stream(|_, sink| {
+    ///     let mut n = 0u64;
+    ///     while sink.send(n) {
+    ///         n += 1;
+    ///     }
+    /// });
+    ///
+    /// // dropping the iterator stops the producer.
+    /// let squares: Vec<_> = naturals.into_iter().map(|n| n * n).take(4).collect();
+    /// assert_eq!(squares, [0, 1, 4, 9]);
+    ///```
+    pub fn spawn_stream<F, I>(f: F) -> StreamThread<I>
+    where
+        F: FnOnce(StopToken, Sink<I>) + Send + 'static,
+        I: Send + 'static,
+    {
+        let (tx, rx) = channel();
+        let thread = Thread::spawn(move |stop| {
+            let sink = Sink {
+                tx,
+                stop: stop.clone(),
+            };
+            f(stop, sink)
+        });
+
+        StreamThread { thread, rx }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stop_producer() {
+        let th = Thread::spawn_stream(|stop, sink| {
+            let mut n = 0;
+            while sink.send(n) {
+                n += 1;
+                stop.wait();
+            }
+        });
+
+        assert_eq!(th.recv(), Some(0));
+        th.stop();
+        assert_eq!(th.iter().count(), 0);
+        th.join().unwrap();
+    }
+}
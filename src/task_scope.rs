@@ -0,0 +1,158 @@
+//! Structured concurrency on top of [`scope`](crate::scope): the first failing child
+//! cancels all of its siblings.
+
+use crate::{scope, Scope, StopToken};
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+
+/// Create a [`TaskScope`] for spawning fallible stoppable children that can borrow
+/// from the enclosing stack.
+///
+/// As soon as any child returns an error or panics, every other child is signaled to stop.
+/// Once `f` returns all children are stopped and joined, then the first failure is
+/// propagated: errors are returned, panics are resumed.
+///```
+/// let data = [1, 2, 3, 4];
+///
+/// let res = stoplight::task_scope(|s| {
+///     s.spawn(|stop| {
+///         // cancelled by its failing sibling.
+///         while !stop.is_stopped() {}
+///         Ok(())
+///     });
+///     s.spawn(|_| match data.iter().sum::<i32>() {
+///         10 => Err("sum too large"),
+///         _ => Ok(()),
+///     });
+/// });
+///
+/// assert_eq!(res, Err("sum too large"));
+///```
+pub fn task_scope<'env, F, R, E>(f: F) -> Result<R, E>
+where
+    F: for<'scope> FnOnce(&TaskScope<'scope, 'env, E>) -> R,
+    E: Send + 'env,
+{
+    let failure = Arc::new(Mutex::new(None));
+
+    let res = scope(|scope| {
+        f(&TaskScope {
+            scope: scope.clone(),
+            failure: failure.clone(),
+        })
+    });
+
+    match Arc::try_unwrap(failure)
+        .ok()
+        .and_then(|f| f.into_inner().unwrap())
+    {
+        None => Ok(res),
+        Some(Failure::Err(err)) => Err(err),
+        Some(Failure::Panic(payload)) => panic::resume_unwind(payload),
+    }
+}
+
+/// A scope whose children are cancelled together, see [`task_scope`].
+pub struct TaskScope<'scope, 'env: 'scope, E> {
+    scope: Scope<'scope, 'env>,
+    failure: Arc<Mutex<Option<Failure<E>>>>,
+}
+
+enum Failure<E> {
+    Err(E),
+    Panic(Box<dyn Any + Send + 'static>),
+}
+
+impl<'scope, 'env, E> TaskScope<'scope, 'env, E>
+where
+    E: Send + 'env,
+{
+    /// Spawn a fallible child within the scope.
+    pub fn spawn<F>(&self, f: F)
+    where
+        F: FnOnce(StopToken) -> Result<(), E> + Send + 'scope,
+    {
+        let scope = self.scope.clone();
+        let failure = self.failure.clone();
+
+        let child = self.scope.spawn(move |stop| {
+            let res = match panic::catch_unwind(AssertUnwindSafe(|| f(stop))) {
+                Ok(Ok(())) => return,
+                Ok(Err(err)) => Failure::Err(err),
+                Err(payload) => Failure::Panic(payload),
+            };
+
+            // record the failure before stopping, so siblings spawned concurrently see it.
+            failure.lock().unwrap().get_or_insert(res);
+            scope.stop_all();
+        });
+
+        if self.is_failed() {
+            child.stop();
+        }
+    }
+
+    /// Signal every child to stop, without failing the scope.
+    pub fn stop_all(&self) {
+        self.scope.stop_all();
+    }
+
+    /// Returns true once a child has returned an error or panicked.
+    pub fn is_failed(&self) -> bool {
+        self.failure.lock().unwrap().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_ok() {
+        let count = AtomicUsize::new(0);
+        let res: Result<_, ()> = task_scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|_| {
+                    count.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                });
+            }
+            "done"
+        });
+
+        assert_eq!(res, Ok("done"));
+        assert_eq!(count.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_panic_cancels_siblings() {
+        let res = panic::catch_unwind(|| {
+            task_scope(|s| {
+                s.spawn(|stop| {
+                    while !stop.is_stopped() {}
+                    Ok::<_, ()>(())
+                });
+                s.spawn(|_| panic!("child panicked"));
+            })
+        });
+
+        let payload = res.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"child panicked"));
+    }
+
+    #[test]
+    fn test_spawn_after_failure() {
+        let res = task_scope(|s| {
+            s.spawn(|_| Err(1));
+            while !s.is_failed() {}
+            s.spawn(|stop| {
+                assert!(stop.is_stopped());
+                Err(2)
+            });
+        });
+
+        assert_eq!(res, Err(1));
+    }
+}
@@ -0,0 +1,602 @@
+//! Stoppable OS threads.
+
+use crate::{timer, StopSource, StopToken};
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Handle to a stoppable thread.
+///
+/// What happens when the handle is dropped without being joined is controlled by its
+/// [`DropPolicy`], by default the thread is signaled to stop.
+pub struct Thread<T> {
+    // only None after being joined.
+    jh: Option<JoinHandle<T>>,
+    stop: StopSource,
+    done: Arc<Completion>,
+    drop_policy: DropPolicy,
+}
+
+/// What to do with a still running thread when its [`Thread`] handle is dropped.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Let the thread keep running in the background.
+    Detach,
+    /// Signal the thread to stop, without waiting for it to exit.
+    #[default]
+    Stop,
+    /// Signal the thread to stop, then block until it exits.
+    StopAndJoin,
+}
+
+impl<T> Thread<T>
+where
+    T: Send + 'static,
+{
+    /// Spawn a new job with cancelation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the OS fails to create a thread, use [`Thread::try_spawn`] to handle this error.
+    pub fn spawn<F>(f: F) -> Thread<T>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        Thread::try_spawn(f).expect("failed to spawn thread")
+    }
+
+    /// Spawn a new job with cancelation, returning an error if the OS failed to create
+    /// the thread (e.g. when hitting resource limits).
+    pub fn try_spawn<F>(f: F) -> io::Result<Thread<T>>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        Thread::spawn_std(thread::Builder::new(), f)
+    }
+
+    /// Spawn a new job with cancelation, which is signaled to stop once `timeout` elapses.
+    ///
+    /// The deadline is tracked by a timer thread shared by all threads, so no extra
+    /// thread is spawned per job.
+    ///```
+    /// use stoplight::Thread;
+    /// use std::time::Duration;
+    ///
+    /// let th = Thread::spawn_with_timeout(Duration::from_millis(10), |stop| {
+    ///     let mut iterations = 0u64;
+    ///     while !stop.is_stopped() {
+    ///         iterations += 1;
+    ///     }
+    ///     iterations
+    /// });
+    /// assert!(th.join().unwrap() > 0);
+    ///```
+    pub fn spawn_with_timeout<F>(timeout: Duration, f: F) -> Thread<T>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        let th = Thread::spawn(f);
+
+        let stop = th.stop.clone();
+        let id = timer::schedule(Instant::now() + timeout, move || stop.stop());
+        // don't keep the source around until the deadline once the thread exited.
+        th.done.on_finish(Box::new(move || timer::cancel(id)));
+
+        th
+    }
+
+    pub(crate) fn spawn_std<F>(builder: thread::Builder, f: F) -> io::Result<Thread<T>>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        let stop = StopSource::new();
+        let token = stop.token();
+        let done = Arc::new(Completion::default());
+
+        let exit = Exit {
+            token: token.clone(),
+            done: done.clone(),
+        };
+
+        let unpark = token.clone();
+        let jh = builder.spawn(move || {
+            // dropped on exit, even when panicking.
+            let _exit = exit;
+            f(token)
+        })?;
+        unpark.unpark_on_stop(jh.thread().clone());
+
+        Ok(Thread {
+            stop,
+            done,
+            jh: Some(jh),
+            drop_policy: DropPolicy::default(),
+        })
+    }
+
+    /// Join waits for the thread to exit then returns the return value.
+    pub fn join(mut self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.jh.take().unwrap().join()
+    }
+
+    /// Join the thread if it has already exited, without blocking.
+    /// If it is still running the handle is given back in `Err`.
+    #[allow(clippy::type_complexity)]
+    pub fn try_join(self) -> Result<Result<T, Box<dyn Any + Send + 'static>>, Thread<T>> {
+        if self.is_finished() {
+            Ok(self.join())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Wait up to `timeout` for the thread to exit then returns the return value.
+    /// If it is still running the handle is given back inside the [`JoinTimeout`] error.
+    #[allow(clippy::type_complexity)]
+    pub fn join_timeout(
+        self,
+        timeout: Duration,
+    ) -> Result<Result<T, Box<dyn Any + Send + 'static>>, JoinTimeout<T>> {
+        if self.done.wait_timeout(timeout) {
+            Ok(self.join())
+        } else {
+            Err(JoinTimeout(self))
+        }
+    }
+
+    /// Returns true once the thread has exited, either by returning or panicking.
+    pub fn is_finished(&self) -> bool {
+        self.jh.as_ref().unwrap().is_finished()
+    }
+
+    /// Returns true once the thread has been signaled to stop.
+    pub fn is_stop_requested(&self) -> bool {
+        self.stop.is_stopped()
+    }
+
+    pub(crate) fn stop_source(&self) -> &StopSource {
+        &self.stop
+    }
+
+    /// Let the thread keep running in the background, giving up the ability to join it.
+    ///
+    /// The returned [`StopSource`] can still be used to signal the thread to stop,
+    /// or dropped if that will never be needed.
+    pub fn detach(mut self) -> StopSource {
+        // without a join handle dropping self does nothing.
+        self.jh.take();
+        std::mem::take(&mut self.stop)
+    }
+
+    /// Set what happens to the thread when this handle is dropped without being joined.
+    pub fn set_drop_policy(&mut self, policy: DropPolicy) {
+        self.drop_policy = policy;
+    }
+
+    /// Signal the Thread to stop, NOTE: This does not ensure the thread has stopped
+    /// but only that the signal has been sent.
+    ///
+    /// The thread is also unparked, waking it up from [`StopToken::park`].
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    /// Ask the thread to pause at its next [`StopToken::wait_if_paused`] checkpoint.
+    pub fn pause(&self) {
+        self.stop.pause();
+    }
+
+    /// Let the paused thread continue.
+    pub fn resume(&self) {
+        self.stop.resume();
+    }
+
+    /// Signal the Thread to stop, then block until the thread has acknowledged it, either
+    /// by observing [`StopToken::is_stopped`] return true or by exiting.
+    /// Returns false if `timeout` elapsed first.
+    pub fn stop_wait(&self, timeout: Duration) -> bool {
+        self.stop();
+        self.stop.wait_acknowledged(timeout)
+    }
+
+    /// Signal the Thread to stop, then wait for it to exit and return its return value.
+    pub fn stop_and_join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        self.stop();
+        self.join()
+    }
+}
+
+impl<T> Thread<Option<T>>
+where
+    T: Send + 'static,
+{
+    /// Spawn a new job with cancelation that only starts running `f` after `delay`.
+    ///
+    /// If stop is requested during the delay `f` never runs and `None` is returned on join.
+    ///```
+    /// use stoplight::Thread;
+    /// use std::time::Duration;
+    ///
+    /// let th = Thread::spawn_after(Duration::from_secs(60), |_| 42);
+    /// assert_eq!(th.stop_and_join().unwrap(), None);
+    ///
+    /// let th = Thread::spawn_after(Duration::from_millis(1), |_| 42);
+    /// assert_eq!(th.join().unwrap(), Some(42));
+    ///```
+    pub fn spawn_after<F>(delay: Duration, f: F) -> Thread<Option<T>>
+    where
+        F: FnOnce(StopToken) -> T + Send + 'static,
+    {
+        Thread::spawn(move |stop| {
+            if stop.wait_timeout(delay) {
+                None
+            } else {
+                Some(f(stop))
+            }
+        })
+    }
+}
+
+impl<T> Drop for Thread<T> {
+    fn drop(&mut self) {
+        let jh = match self.jh.take() {
+            Some(jh) => jh,
+            None => return,
+        };
+
+        match self.drop_policy {
+            DropPolicy::Detach => {}
+            DropPolicy::Stop => self.stop.stop(),
+            DropPolicy::StopAndJoin => {
+                self.stop.stop();
+                // a panic in the thread is dropped along with the handle.
+                let _ = jh.join();
+            }
+        }
+    }
+}
+
+/// Block until the first of `threads` exits, returning its index, its result,
+/// and the remaining threads in their original order.
+///
+/// Dropping the remaining threads signals them to stop, unless their [`DropPolicy`] was changed.
+///```
+/// use stoplight::Thread;
+/// use std::time::Duration;
+///
+/// let threads = vec![
+///     Thread::spawn(|stop| while !stop.is_stopped() {}),
+///     Thread::spawn(|_| std::thread::sleep(Duration::from_millis(10))),
+/// ];
+///
+/// let (index, res, remaining) = stoplight::join_any(threads);
+/// assert_eq!(index, 1);
+/// assert!(res.is_ok());
+/// assert_eq!(remaining.len(), 1);
+///```
+///
+/// # Panics
+///
+/// Panics if `threads` is empty.
+#[allow(clippy::type_complexity)]
+pub fn join_any<T>(
+    mut threads: Vec<Thread<T>>,
+) -> (
+    usize,
+    Result<T, Box<dyn Any + Send + 'static>>,
+    Vec<Thread<T>>,
+)
+where
+    T: Send + 'static,
+{
+    assert!(!threads.is_empty(), "join_any called without threads");
+
+    let (tx, rx) = mpsc::channel();
+    let ids: Vec<_> = threads
+        .iter()
+        .enumerate()
+        .map(|(i, th)| {
+            let tx = tx.clone();
+            th.done.on_finish(Box::new(move || {
+                let _ = tx.send(i);
+            }))
+        })
+        .collect();
+
+    // we hold a sender ourselves, so this never hangs up.
+    let index = rx.recv().unwrap();
+    for (th, id) in threads.iter().zip(ids) {
+        if let Some(id) = id {
+            th.done.remove(id);
+        }
+    }
+
+    let res = threads.remove(index).join();
+    (index, res, threads)
+}
+
+/// Error returned by [`Thread::join_timeout`] when the thread did not exit in time.
+pub struct JoinTimeout<T>(Thread<T>);
+
+impl<T> JoinTimeout<T> {
+    /// Get back the handle of the thread that is still running.
+    pub fn into_inner(self) -> Thread<T> {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for JoinTimeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JoinTimeout { .. }")
+    }
+}
+
+impl<T> fmt::Display for JoinTimeout<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("timed out waiting for thread to exit")
+    }
+}
+
+impl<T> Error for JoinTimeout<T> {}
+
+/// Set once the spawned closure has returned or panicked.
+#[derive(Default)]
+struct Completion {
+    state: Mutex<CompletionState>,
+    cvar: Condvar,
+}
+
+#[derive(Default)]
+struct CompletionState {
+    finished: bool,
+    next_id: usize,
+    // run once on finish, removable by id.
+    callbacks: Vec<(usize, Box<dyn FnOnce() + Send>)>,
+}
+
+impl Completion {
+    fn finish(&self) {
+        let callbacks = {
+            let mut state = self.state.lock().unwrap();
+            state.finished = true;
+            std::mem::take(&mut state.callbacks)
+        };
+        self.cvar.notify_all();
+
+        for (_, f) in callbacks {
+            f();
+        }
+    }
+
+    fn wait_timeout(&self, timeout: Duration) -> bool {
+        let guard = self.state.lock().unwrap();
+        let (guard, _) = self
+            .cvar
+            .wait_timeout_while(guard, timeout, |state| !state.finished)
+            .unwrap();
+        guard.finished
+    }
+
+    /// Register `f` to run once finished, or run it right away if already finished.
+    /// Returns an id for [`remove`](Completion::remove) if `f` was registered.
+    fn on_finish(&self, f: Box<dyn FnOnce() + Send>) -> Option<usize> {
+        let mut state = self.state.lock().unwrap();
+        if state.finished {
+            drop(state);
+            f();
+            return None;
+        }
+
+        let id = state.next_id;
+        state.next_id += 1;
+        state.callbacks.push((id, f));
+        Some(id)
+    }
+
+    fn remove(&self, id: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .callbacks
+            .retain(|(i, _)| *i != id);
+    }
+}
+
+/// Guard owned by the spawned thread, marking it as exited when dropped.
+struct Exit {
+    token: StopToken,
+    done: Arc<Completion>,
+}
+
+impl Drop for Exit {
+    fn drop(&mut self) {
+        // exiting acknowledges any pending stop.
+        self.token.acknowledge();
+        self.done.finish();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busy_loop() {
+        let th = Thread::spawn(|stop| {
+            thread::sleep(Duration::from_millis(300));
+            while !stop.is_stopped() {}
+            42
+        });
+
+        th.stop();
+        assert_eq!(th.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_try_spawn() {
+        let th = Thread::try_spawn(|_| 42).unwrap();
+        assert_eq!(th.join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_stop_and_join() {
+        let th = Thread::spawn(|stop| {
+            while !stop.is_stopped() {}
+            42
+        });
+
+        assert_eq!(th.stop_and_join().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_state_accessors() {
+        let th = Thread::spawn(|stop| while !stop.is_stopped() {});
+        assert!(!th.is_finished());
+        assert!(!th.is_stop_requested());
+
+        th.stop();
+        assert!(th.is_stop_requested());
+        while !th.is_finished() {
+            thread::yield_now();
+        }
+        th.join().unwrap();
+    }
+
+    #[test]
+    fn test_drop_policy() {
+        let source = StopSource::new();
+        let observer = source.token();
+        let th = Thread::spawn(move |stop| {
+            while !stop.is_stopped() {}
+            source.stop();
+        });
+        drop(th);
+        while !observer.is_stopped() {
+            thread::yield_now();
+        }
+
+        let mut th = Thread::spawn(|stop| {
+            while !stop.is_stopped() {}
+            thread::sleep(Duration::from_millis(100));
+        });
+        let done = th.done.clone();
+        th.set_drop_policy(DropPolicy::StopAndJoin);
+        drop(th);
+        assert!(done.wait_timeout(Duration::from_secs(0)));
+
+        let release = StopSource::new();
+        let released = release.token();
+        let mut th =
+            Thread::spawn(move |stop| while !stop.is_stopped() && !released.is_stopped() {});
+        let token = th.stop.token();
+        th.set_drop_policy(DropPolicy::Detach);
+        drop(th);
+        assert!(!token.is_stopped());
+        release.stop();
+    }
+
+    #[test]
+    fn test_detach() {
+        let done = StopSource::new();
+        let finished = done.token();
+        let source = Thread::spawn(move |stop| {
+            while !stop.is_stopped() {}
+            done.stop();
+        })
+        .detach();
+        assert!(!finished.is_stopped());
+
+        source.stop();
+        while !finished.is_stopped() {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_join_any() {
+        let threads: Vec<_> = (0..3)
+            .map(|i| {
+                Thread::spawn(move |stop| {
+                    while i != 2 && !stop.is_stopped() {}
+                    i
+                })
+            })
+            .collect();
+
+        let (index, res, remaining) = join_any(threads);
+        assert_eq!(index, 2);
+        assert_eq!(res.unwrap(), 2);
+
+        let (index, res, remaining) = join_any(
+            remaining
+                .into_iter()
+                .inspect(|th| th.stop())
+                .collect::<Vec<_>>(),
+        );
+        assert!(index < 2);
+        assert_eq!(res.unwrap(), index);
+        assert_eq!(remaining.len(), 1);
+    }
+
+    #[test]
+    fn test_stop_unparks() {
+        let th = Thread::spawn(|stop| {
+            thread::park_timeout(Duration::from_secs(60));
+            stop.is_stopped()
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(th.stop_and_join().unwrap());
+    }
+
+    #[test]
+    fn test_try_join() {
+        let mut th = Thread::spawn(|stop| {
+            while !stop.is_stopped() {}
+            42
+        });
+        th = th.try_join().expect_err("thread exited before stop");
+
+        th.stop();
+        loop {
+            match th.try_join() {
+                Ok(res) => break assert_eq!(res.unwrap(), 42),
+                Err(t) => th = t,
+            }
+        }
+    }
+
+    #[test]
+    fn test_join_timeout() {
+        let th = Thread::spawn(|stop| {
+            while !stop.is_stopped() {}
+            42
+        });
+        let th = th
+            .join_timeout(Duration::from_millis(10))
+            .expect_err("thread exited before stop")
+            .into_inner();
+
+        th.stop();
+        let res = th.join_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(res.unwrap(), 42);
+    }
+
+    #[test]
+    fn test_stop_wait() {
+        let th = Thread::spawn(|stop| {
+            while !stop.is_stopped() {}
+            thread::sleep(Duration::from_millis(100));
+        });
+        assert!(th.stop_wait(Duration::from_secs(5)));
+
+        let th = Thread::spawn(|_| thread::sleep(Duration::from_millis(300)));
+        assert!(!th.stop_wait(Duration::from_millis(10)));
+        assert!(th.stop_wait(Duration::from_secs(5)));
+    }
+}
@@ -0,0 +1,103 @@
+//! A single background thread running callbacks at deadlines, so timeouts don't need
+//! a thread of their own.
+
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::Instant;
+
+type Callback = Box<dyn FnOnce() + Send + 'static>;
+
+/// Identifies a scheduled callback, to cancel it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct TimerId(Instant, u64);
+
+#[derive(Default)]
+struct Timer {
+    state: Mutex<State>,
+    cvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    callbacks: BTreeMap<TimerId, Callback>,
+}
+
+fn timer() -> &'static Timer {
+    static TIMER: OnceLock<&'static Timer> = OnceLock::new();
+
+    TIMER.get_or_init(|| {
+        let timer: &'static Timer = Box::leak(Box::default());
+        thread::Builder::new()
+            .name("stoplight-timer".into())
+            .spawn(move || timer.run())
+            .expect("failed to spawn timer thread");
+        timer
+    })
+}
+
+/// Run `f` on the timer thread once `deadline` is reached.
+pub(crate) fn schedule<F>(deadline: Instant, f: F) -> TimerId
+where
+    F: FnOnce() + Send + 'static,
+{
+    let timer = timer();
+    let mut state = timer.state.lock().unwrap();
+    let id = TimerId(deadline, state.next_id);
+    state.next_id += 1;
+    state.callbacks.insert(id, Box::new(f));
+
+    // the deadline may be earlier than the one the timer is sleeping until.
+    timer.cvar.notify_one();
+    id
+}
+
+/// Cancel a callback, does nothing if it already ran.
+pub(crate) fn cancel(id: TimerId) {
+    timer().state.lock().unwrap().callbacks.remove(&id);
+}
+
+impl Timer {
+    fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let next = state.callbacks.keys().next().copied();
+            let now = Instant::now();
+            state = match next {
+                None => self.cvar.wait(state).unwrap(),
+                Some(id) if id.0 > now => self.cvar.wait_timeout(state, id.0 - now).unwrap().0,
+                Some(id) => {
+                    let f = state.callbacks.remove(&id).unwrap();
+                    drop(state);
+                    f();
+                    self.state.lock().unwrap()
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+    use std::time::Duration;
+
+    #[test]
+    fn test_schedule_order_and_cancel() {
+        let (tx, rx) = channel();
+        let now = Instant::now();
+
+        for i in [3, 1, 2] {
+            let tx = tx.clone();
+            schedule(now + Duration::from_millis(i * 20), move || {
+                tx.send(i).unwrap()
+            });
+        }
+        let cancelled = schedule(now + Duration::from_millis(10), move || tx.send(0).unwrap());
+        cancel(cancelled);
+
+        assert_eq!(rx.iter().collect::<Vec<_>>(), [1, 2, 3]);
+    }
+}
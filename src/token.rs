@@ -0,0 +1,647 @@
+//! Cancellation primitives, modeled after C++20's `stop_source`/`stop_token`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, Weak};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The requesting side of a cancellation signal.
+///
+/// A `StopSource` hands out any number of [`StopToken`]s with [`token`](StopSource::token),
+/// and signals all of them at once with [`stop`](StopSource::stop).
+///```
+/// use stoplight::StopSource;
+///
+/// let source = StopSource::new();
+/// let token = source.token();
+///
+/// let th = std::thread::spawn(move || while !token.is_stopped() {});
+/// source.stop();
+/// th.join().unwrap();
+///```
+///
+/// Cloning a source gives another handle to the same signal.
+#[derive(Clone, Debug, Default)]
+pub struct StopSource {
+    inner: Arc<Inner>,
+}
+
+/// The observing side of a cancellation signal, handed to stoppable tasks.
+///
+/// Tokens are cheap to clone and can be sent to other threads.
+#[derive(Clone, Debug)]
+pub struct StopToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    stopped: AtomicBool,
+    // Set once a token observed the stop signal, see `StopSource::wait_acknowledged`.
+    acked: AtomicBool,
+    // workers block in `StopToken::wait_if_paused` while set.
+    paused: AtomicBool,
+    // nanoseconds since `epoch()` of the last heartbeat, see `StopToken::heartbeat`.
+    heartbeat: AtomicU64,
+    state: Mutex<State>,
+    cvar: Condvar,
+}
+
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // notified once stopped, taken when stopping.
+    listeners: Vec<Listener>,
+}
+
+/// Something to notify when a signal is stopped.
+enum Listener {
+    Child(Weak<Inner>),
+    AllOf(Arc<AllOf>),
+    Callback(Box<dyn FnOnce() + Send + 'static>),
+}
+
+impl fmt::Debug for Listener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Listener::Child(child) => f.debug_tuple("Child").field(child).finish(),
+            Listener::AllOf(all) => f.debug_tuple("AllOf").field(all).finish(),
+            Listener::Callback(_) => f.write_str("Callback(..)"),
+        }
+    }
+}
+
+/// Stops `target` once `remaining` signals have stopped.
+#[derive(Debug)]
+struct AllOf {
+    target: Weak<Inner>,
+    remaining: AtomicUsize,
+}
+
+impl Listener {
+    fn notify(self) {
+        match self {
+            Listener::Child(child) => {
+                if let Some(child) = child.upgrade() {
+                    child.stop();
+                }
+            }
+            Listener::AllOf(all) => {
+                if all.remaining.fetch_sub(1, Ordering::Relaxed) == 1 {
+                    if let Some(target) = all.target.upgrade() {
+                        target.stop();
+                    }
+                }
+            }
+            Listener::Callback(f) => f(),
+        }
+    }
+
+    fn is_dead(&self) -> bool {
+        match self {
+            Listener::Child(child) => child.strong_count() == 0,
+            Listener::AllOf(all) => all.target.strong_count() == 0,
+            Listener::Callback(_) => false,
+        }
+    }
+}
+
+impl Inner {
+    fn stop(&self) {
+        if self.stopped.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let listeners = {
+            let mut state = self.state.lock().unwrap();
+            self.cvar.notify_all();
+            std::mem::take(&mut state.listeners)
+        };
+        for listener in listeners {
+            listener.notify();
+        }
+    }
+
+    /// Notify `listener` once stopped, or right away if already stopped.
+    fn listen(&self, listener: Listener) {
+        let mut state = self.state.lock().unwrap();
+        // checked under the lock, otherwise the listener could be added after stop took them.
+        if self.stopped.load(Ordering::Relaxed) {
+            drop(state);
+            listener.notify();
+        } else {
+            state.listeners.retain(|l| !l.is_dead());
+            state.listeners.push(listener);
+        }
+    }
+
+    fn wait_timeout(&self, timeout: Option<Duration>) -> bool {
+        let guard = self.state.lock().unwrap();
+        let stopped = |_: &mut State| !self.stopped.load(Ordering::Relaxed);
+        match timeout {
+            Some(timeout) => drop(
+                self.cvar
+                    .wait_timeout_while(guard, timeout, stopped)
+                    .unwrap(),
+            ),
+            None => drop(self.cvar.wait_while(guard, stopped).unwrap()),
+        }
+
+        let stopped = self.stopped.load(Ordering::Relaxed);
+        if stopped {
+            self.acknowledge();
+        }
+        stopped
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+        let _guard = self.state.lock().unwrap();
+        self.cvar.notify_all();
+    }
+
+    fn wait_if_paused(&self) -> bool {
+        let guard = self.state.lock().unwrap();
+        let _guard = self
+            .cvar
+            .wait_while(guard, |_| {
+                self.paused.load(Ordering::Relaxed) && !self.stopped.load(Ordering::Relaxed)
+            })
+            .unwrap();
+
+        let stopped = self.stopped.load(Ordering::Relaxed);
+        if stopped {
+            self.acknowledge();
+        }
+        stopped
+    }
+
+    fn child(&self) -> StopSource {
+        let child = StopSource::new();
+        self.listen(Listener::Child(Arc::downgrade(&child.inner)));
+        child
+    }
+
+    fn acknowledge(&self) {
+        if !self.acked.load(Ordering::Relaxed) && !self.acked.swap(true, Ordering::Relaxed) {
+            let _guard = self.state.lock().unwrap();
+            self.cvar.notify_all();
+        }
+    }
+}
+
+impl StopSource {
+    /// Create a new source that has not been stopped.
+    pub fn new() -> StopSource {
+        StopSource::default()
+    }
+
+    /// Create a token observing this source.
+    pub fn token(&self) -> StopToken {
+        StopToken {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Signal every token of this source to stop, including those of child sources.
+    pub fn stop(&self) {
+        self.inner.stop();
+    }
+
+    /// Create a child source, stopped along with this one but which can also be stopped
+    /// independently, see [`StopToken::child`].
+    pub fn child(&self) -> StopSource {
+        self.inner.child()
+    }
+
+    /// Returns true once stop has been signaled.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Ask tasks to pause at their next [`StopToken::wait_if_paused`] checkpoint.
+    /// Pausing only applies to this signal, not to its children.
+    pub fn pause(&self) {
+        self.inner.set_paused(true);
+    }
+
+    /// Let paused tasks continue.
+    pub fn resume(&self) {
+        self.inner.set_paused(false);
+    }
+
+    /// Returns true while paused.
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::Relaxed)
+    }
+
+    /// Time of the last [`StopToken::heartbeat`], or `None` if there wasn't any.
+    pub(crate) fn last_heartbeat(&self) -> Option<Instant> {
+        match self.inner.heartbeat.load(Ordering::Relaxed) {
+            0 => None,
+            nanos => Some(epoch() + Duration::from_nanos(nanos)),
+        }
+    }
+
+    /// Block until a token has observed the stop signal, or `timeout` elapses.
+    /// Returns true if the stop was acknowledged.
+    pub(crate) fn wait_acknowledged(&self, timeout: Duration) -> bool {
+        let guard = self.inner.state.lock().unwrap();
+        let (_guard, res) = self
+            .inner
+            .cvar
+            .wait_timeout_while(guard, timeout, |_| {
+                !self.inner.acked.load(Ordering::Relaxed)
+            })
+            .unwrap();
+
+        !res.timed_out()
+    }
+}
+
+impl StopToken {
+    /// Returns true once the task has been signaled to stop.
+    ///
+    /// The first time this returns true the stop counts as acknowledged,
+    /// see [`Thread::stop_wait`](crate::Thread::stop_wait).
+    pub fn is_stopped(&self) -> bool {
+        let stopped = self.inner.stopped.load(Ordering::Relaxed);
+        if stopped {
+            self.inner.acknowledge();
+        }
+        stopped
+    }
+
+    /// Block until stop is requested, without spinning.
+    pub fn wait(&self) {
+        self.inner.wait_timeout(None);
+    }
+
+    /// Block until stop is requested or `timeout` elapses, whichever comes first.
+    /// Returns true if stop was requested.
+    ///```
+    /// use stoplight::StopSource;
+    /// use std::time::Duration;
+    ///
+    /// let source = StopSource::new();
+    /// let token = source.token();
+    /// assert!(!token.wait_timeout(Duration::from_millis(10)));
+    ///
+    /// source.stop();
+    /// assert!(token.wait_timeout(Duration::from_secs(10)));
+    ///```
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.inner.wait_timeout(Some(timeout))
+    }
+
+    /// Returns true while the task is asked to pause, see [`StopSource::pause`].
+    pub fn is_paused(&self) -> bool {
+        self.inner.paused.load(Ordering::Relaxed)
+    }
+
+    /// Checkpoint blocking while the task is paused, until it is resumed or stopped.
+    /// Returns true if stop was requested.
+    ///```
+    /// use stoplight::Thread;
+    ///
+    /// let th = Thread::spawn(|stop| {
+    ///     let mut batches = 0;
+    ///     while !stop.wait_if_paused() {
+    ///         batches += 1;
+    ///     }
+    ///     batches
+    /// });
+    ///
+    /// th.pause();
+    /// th.resume();
+    /// th.stop_and_join().unwrap();
+    ///```
+    pub fn wait_if_paused(&self) -> bool {
+        self.inner.wait_if_paused()
+    }
+
+    /// Park the current thread until it is unparked or stop is requested, see [`std::thread::park`].
+    ///
+    /// Stop only unparks threads spawned by stoplight with this token. Like `park`,
+    /// this may also return spuriously.
+    ///```
+    /// use stoplight::Thread;
+    ///
+    /// let th = Thread::spawn(|stop| {
+    ///     while !stop.is_stopped() {
+    ///         stop.park();
+    ///     }
+    /// });
+    /// th.stop_and_join().unwrap();
+    ///```
+    pub fn park(&self) {
+        if !self.is_stopped() {
+            thread::park();
+        }
+    }
+
+    /// Like [`park`](StopToken::park), but waking up after at most `timeout`.
+    pub fn park_timeout(&self, timeout: Duration) {
+        if !self.is_stopped() {
+            thread::park_timeout(timeout);
+        }
+    }
+
+    pub(crate) fn unpark_on_stop(&self, th: thread::Thread) {
+        self.on_stop(move || th.unpark());
+    }
+
+    /// Create a child source, which is stopped when this token is stopped but can also be
+    /// stopped on its own without affecting this token.
+    ///```
+    /// use stoplight::StopSource;
+    ///
+    /// let shutdown = StopSource::new();
+    /// let connection = shutdown.token().child();
+    ///
+    /// connection.stop();
+    /// assert!(!shutdown.is_stopped());
+    ///
+    /// let connection = shutdown.token().child();
+    /// shutdown.stop();
+    /// assert!(connection.is_stopped());
+    ///```
+    pub fn child(&self) -> StopSource {
+        self.inner.child()
+    }
+
+    /// Register `f` to be called when stop is requested, e.g. to unblock a pending read by
+    /// shutting down its socket.
+    ///
+    /// `f` runs on the thread requesting the stop, or right away on this thread if stop
+    /// was already requested. It should be quick, since it delays the return of `stop()`.
+    ///```
+    /// use stoplight::StopSource;
+    /// use std::sync::mpsc::channel;
+    ///
+    /// let source = StopSource::new();
+    /// let (tx, rx) = channel();
+    /// source.token().on_stop(move || tx.send("stopped").unwrap());
+    ///
+    /// source.stop();
+    /// assert_eq!(rx.try_recv(), Ok("stopped"));
+    ///```
+    pub fn on_stop<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.inner.listen(Listener::Callback(Box::new(f)));
+    }
+
+    /// Get a channel receiver that disconnects once stop is requested, so the stop signal can
+    /// be waited on in a [`crossbeam_channel::select!`] alongside other channels.
+    /// No message is ever sent on it.
+    ///```
+    /// use crossbeam_channel::{select, unbounded};
+    /// use stoplight::Thread;
+    ///
+    /// let (jobs, rx) = unbounded::<u32>();
+    /// let th = Thread::spawn(move |stop| {
+    ///     let stopped = stop.as_receiver();
+    ///     let mut sum = 0;
+    ///     loop {
+    ///         select! {
+    ///             recv(rx) -> job => sum += job.unwrap(),
+    ///             recv(stopped) -> _ => return sum,
+    ///         }
+    ///     }
+    /// });
+    ///
+    /// jobs.send(2).unwrap();
+    /// # while !jobs.is_empty() {}
+    /// assert_eq!(th.stop_and_join().unwrap(), 2);
+    ///```
+    #[cfg(feature = "crossbeam")]
+    pub fn as_receiver(&self) -> crossbeam_channel::Receiver<()> {
+        let (tx, rx) = crossbeam_channel::bounded(0);
+        self.on_stop(move || drop(tx));
+        rx
+    }
+
+    /// Create a token that is stopped as soon as any of `tokens` is stopped.
+    /// Without any tokens it is never stopped.
+    ///```
+    /// use stoplight::{StopSource, StopToken};
+    ///
+    /// let shutdown = StopSource::new();
+    /// let reload = StopSource::new();
+    /// let token = StopToken::any_of(&[shutdown.token(), reload.token()]);
+    ///
+    /// reload.stop();
+    /// assert!(token.is_stopped());
+    ///```
+    pub fn any_of(tokens: &[StopToken]) -> StopToken {
+        let source = StopSource::new();
+        for token in tokens {
+            token
+                .inner
+                .listen(Listener::Child(Arc::downgrade(&source.inner)));
+        }
+        source.token()
+    }
+
+    /// Create a token that is stopped once all of `tokens` are stopped.
+    /// Without any tokens it is stopped right away.
+    pub fn all_of(tokens: &[StopToken]) -> StopToken {
+        let source = StopSource::new();
+        if tokens.is_empty() {
+            source.stop();
+        }
+
+        let all = Arc::new(AllOf {
+            target: Arc::downgrade(&source.inner),
+            remaining: AtomicUsize::new(tokens.len()),
+        });
+        for token in tokens {
+            token.inner.listen(Listener::AllOf(all.clone()));
+        }
+        source.token()
+    }
+
+    /// Signal that the task is still making progress, see [`Watchdog`](crate::watchdog::Watchdog).
+    pub fn heartbeat(&self) {
+        // zero means no heartbeat yet.
+        let nanos = epoch().elapsed().as_nanos().max(1) as u64;
+        self.inner.heartbeat.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Acknowledge the stop regardless of whether it was observed, used when the task exits.
+    pub(crate) fn acknowledge(&self) {
+        self.inner.acknowledge();
+    }
+}
+
+/// Sleep for `duration`, waking up early if `token` is stopped.
+/// Returns true if the full duration elapsed, false if stop was requested.
+///```
+/// use stoplight::Thread;
+/// use std::time::Duration;
+///
+/// let th = Thread::spawn(|stop| {
+///     let mut ticks = 0;
+///     while stoplight::sleep(&stop, Duration::from_secs(60)) {
+///         ticks += 1;
+///     }
+///     ticks
+/// });
+///
+/// // returns right away instead of after a minute.
+/// assert_eq!(th.stop_and_join().unwrap(), 0);
+///```
+pub fn sleep(token: &StopToken, duration: Duration) -> bool {
+    !token.wait_timeout(duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<StopSource>();
+        assert_send_sync::<StopToken>();
+    }
+
+    #[test]
+    fn test_stop_reaches_all_tokens() {
+        let source = StopSource::new();
+        let a = source.token();
+        let b = a.clone();
+        assert!(!a.is_stopped() && !b.is_stopped());
+
+        source.stop();
+        assert!(source.is_stopped());
+        assert!(a.is_stopped() && b.is_stopped());
+    }
+
+    #[test]
+    fn test_child_tree() {
+        let root = StopSource::new();
+        let child = root.child();
+        let grandchild = child.token().child();
+        let sibling = root.child();
+
+        child.stop();
+        assert!(grandchild.is_stopped());
+        assert!(!root.is_stopped() && !sibling.is_stopped());
+
+        root.stop();
+        assert!(sibling.is_stopped());
+        assert!(root.child().is_stopped());
+    }
+
+    #[test]
+    fn test_any_of() {
+        let a = StopSource::new();
+        let b = StopSource::new();
+        let any = StopToken::any_of(&[a.token(), b.token()]);
+        assert!(!any.is_stopped());
+
+        b.stop();
+        assert!(any.is_stopped());
+        assert!(!StopToken::any_of(&[]).is_stopped());
+    }
+
+    #[test]
+    fn test_all_of() {
+        let a = StopSource::new();
+        let b = StopSource::new();
+        a.stop();
+        let all = StopToken::all_of(&[a.token(), b.token()]);
+        assert!(!all.is_stopped());
+
+        b.stop();
+        assert!(all.is_stopped());
+        assert!(StopToken::all_of(&[]).is_stopped());
+    }
+
+    #[test]
+    fn test_on_stop() {
+        let source = StopSource::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let c = calls.clone();
+        let child = source.child();
+        child.token().on_stop(move || {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        source.stop();
+        source.stop();
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+
+        let c = calls.clone();
+        source.token().on_stop(move || {
+            c.fetch_add(1, Ordering::Relaxed);
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_wait() {
+        let source = StopSource::new();
+        let token = source.child().token();
+        let waiter = std::thread::spawn(move || token.wait());
+
+        std::thread::sleep(Duration::from_millis(50));
+        source.stop();
+        waiter.join().unwrap();
+    }
+
+    #[test]
+    fn test_wait_timeout_wakes_on_stop() {
+        let source = StopSource::new();
+        let token = source.token();
+        let waiter = std::thread::spawn(move || {
+            let start = std::time::Instant::now();
+            assert!(token.wait_timeout(Duration::from_secs(60)));
+            start.elapsed()
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        source.stop();
+        assert!(waiter.join().unwrap() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_pause() {
+        let source = StopSource::new();
+        let token = source.token();
+        let (tx, rx) = std::sync::mpsc::channel();
+        source.pause();
+
+        let worker = std::thread::spawn(move || {
+            let stopped = token.wait_if_paused();
+            tx.send(stopped).unwrap();
+            token.wait_if_paused()
+        });
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+
+        source.resume();
+        assert_eq!(rx.recv(), Ok(false));
+        source.pause();
+        source.stop();
+        assert!(worker.join().unwrap());
+    }
+
+    #[test]
+    fn test_acknowledge_on_observe() {
+        let source = StopSource::new();
+        let token = source.token();
+
+        source.stop();
+        assert!(!source.wait_acknowledged(Duration::from_millis(10)));
+        assert!(token.is_stopped());
+        assert!(source.wait_acknowledged(Duration::from_millis(10)));
+    }
+}
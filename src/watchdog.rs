@@ -0,0 +1,121 @@
+//! Detecting and stopping wedged workers that stopped heartbeating.
+
+use crate::{StopSource, Thread};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Signals stop to watched threads that didn't call [`StopToken::heartbeat`](crate::StopToken::heartbeat)
+/// within the configured timeout, and reports them to a handler.
+///```
+/// use stoplight::watchdog::Watchdog;
+/// use stoplight::Thread;
+/// use std::sync::mpsc::channel;
+/// use std::time::Duration;
+///
+/// let (tx, rx) = channel();
+/// let watchdog = Watchdog::new(Duration::from_millis(50), move |name| {
+///     tx.send(name.to_string()).unwrap();
+/// });
+///
+/// let th = Thread::spawn(|stop| {
+///     stop.heartbeat();
+///     // wedged, the watchdog stops us.
+///     stop.wait();
+/// });
+/// watchdog.watch("indexer", &th);
+///
+/// assert_eq!(rx.recv().unwrap(), "indexer");
+/// th.join().unwrap();
+///```
+pub struct Watchdog {
+    watched: Arc<Mutex<Vec<Watched>>>,
+    // checks for missed heartbeats until the watchdog is dropped.
+    _checker: Thread<()>,
+}
+
+struct Watched {
+    name: String,
+    stop: StopSource,
+    // used until the first heartbeat.
+    since: Instant,
+}
+
+impl Watchdog {
+    /// Create a watchdog stopping threads that didn't heartbeat for `timeout`, calling
+    /// `on_timeout` with the name of each thread it stopped.
+    pub fn new<F>(timeout: Duration, mut on_timeout: F) -> Watchdog
+    where
+        F: FnMut(&str) + Send + 'static,
+    {
+        let watched: Arc<Mutex<Vec<Watched>>> = Arc::default();
+
+        let checked = watched.clone();
+        let checker = Thread::spawn(move |stop| {
+            while !stop.wait_timeout(timeout / 4) {
+                let now = Instant::now();
+                let mut timed_out = Vec::new();
+
+                checked.lock().unwrap().retain(|w| {
+                    let last = w.stop.last_heartbeat().unwrap_or(w.since).max(w.since);
+                    if now.duration_since(last) > timeout {
+                        w.stop.stop();
+                        timed_out.push(w.name.clone());
+                    }
+                    // stopping threads are no longer watched.
+                    !w.stop.is_stopped()
+                });
+
+                // not holding the lock, so the handler can watch other threads.
+                for name in timed_out {
+                    on_timeout(&name);
+                }
+            }
+        });
+
+        Watchdog {
+            watched,
+            _checker: checker,
+        }
+    }
+
+    /// Watch `th` under `name`, it must heartbeat within the timeout from now on.
+    /// Threads are no longer watched once they are signaled to stop.
+    pub fn watch<T>(&self, name: &str, th: &Thread<T>)
+    where
+        T: Send + 'static,
+    {
+        self.watched.lock().unwrap().push(Watched {
+            name: name.to_string(),
+            stop: th.stop_source().clone(),
+            since: Instant::now(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_heartbeating_thread_survives() {
+        let (tx, rx) = channel();
+        let watchdog = Watchdog::new(Duration::from_millis(40), move |name| {
+            tx.send(name.to_string()).unwrap();
+        });
+
+        let alive = Thread::spawn(|stop| {
+            while !stop.wait_timeout(Duration::from_millis(5)) {
+                stop.heartbeat();
+            }
+        });
+        let wedged = Thread::spawn(|stop| stop.wait());
+        watchdog.watch("alive", &alive);
+        watchdog.watch("wedged", &wedged);
+
+        assert_eq!(rx.recv().unwrap(), "wedged");
+        wedged.join().unwrap();
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        assert!(!alive.is_stop_requested());
+    }
+}